@@ -1,4 +1,11 @@
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde_derive;
+
 use std::collections::vec_deque::VecDeque;
+use std::ops::{Add, Sub};
 use std::result as result;
 
 pub trait TimeMachineState<F, R> {
@@ -19,7 +26,8 @@ pub struct TimeMachine<S, F, R, T> {
     current: S,
     reverse: VecDeque<Timestamped<T, (F, R)>>,
     forward: Vec<Timestamped<T, F>>,
-    oldest: Option<T>
+    oldest: Option<T>,
+    current_time: Option<T>
 }
 
 impl <S, F, R, T> TimeMachine<S, F, R, T>
@@ -30,7 +38,8 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
             current: initial,
             reverse: VecDeque::new(),
             forward: Vec::new(),
-            oldest: None
+            oldest: None,
+            current_time: None
         }
     }
 
@@ -47,6 +56,125 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
         Ok(&self.current)
     }
 
+    /// Returns a streaming iterator over `(T, &S)`, one entry per
+    /// committed change timestamp in `[from, to]`, walking `forward` one
+    /// delta at a time and borrowing `current` after each `apply_forward`.
+    /// This materializes a trajectory in time linear in the number of
+    /// deltas in the window, rather than calling `value_at` per point
+    /// (which would re-seek from scratch each time).
+    ///
+    /// Since each item borrows from the machine, `StatesBetween` isn't a
+    /// `std::iter::Iterator` — drive it with `while let Some(..) = iter.next()`.
+    pub fn states_between(&mut self, from: T, to: T) -> Result<StatesBetween<S, F, R, T>, T> {
+        try!(self.check_oldest(from));
+        self.move_to(from);
+
+        // `move_to` treats `from` as already applied (it's inclusive on
+        // the seek side too), but `[from, to]` means a change timestamped
+        // exactly `from` must still come out of `next()`. Un-apply it so
+        // the first `next()` call picks it back up like any other change
+        // in the window.
+        if let Some(&Timestamped(time, _)) = self.reverse.back() {
+            if time == from {
+                let Timestamped(time, (delta_f, delta_r)) = self.reverse.pop_back().unwrap();
+                self.current.apply_reverse(&delta_r);
+                self.forward.push(Timestamped(time, delta_f));
+            }
+        }
+
+        Ok(StatesBetween { machine: self, to: to })
+    }
+
+    /// Moves back across the `n`th previously recorded change at or before
+    /// the current time, clamping at the earliest change still on record
+    /// (or at `oldest`, if no recorded change at or before the current time
+    /// is left after `forget_ancient_history`). A no-op if nothing has been
+    /// sought or changed yet; otherwise, if the current time sits between
+    /// changes, `n = 0` still lands on the nearest one at or before it
+    /// (symmetric with `step_forward(0)` landing on the nearest one at or
+    /// after it) rather than leaving the position unchanged.
+    pub fn step_back(&mut self, n: usize) -> Result<&S, T> {
+        let current = match self.current_time {
+            Some(current) => current,
+            None => return Ok(&self.current)
+        };
+        self.move_to(current);
+
+        // `reverse` holds exactly the committed changes at or before
+        // `current`, oldest-to-newest, so the `n`th one back is a
+        // bounded-index lookup rather than a re-sort of the whole history.
+        let target = match self.reverse.len() {
+            0 => match self.oldest {
+                Some(oldest) => oldest,
+                None => current
+            },
+            len => self.reverse[len - 1 - n.min(len - 1)].0
+        };
+
+        self.value_at(target)
+    }
+
+    /// Moves forward across the `n`th recorded change at or after the
+    /// current time, clamping at the most recent recorded change. A no-op
+    /// if nothing has been sought or changed yet; otherwise, if the
+    /// current time sits between changes, `n = 0` still lands on the
+    /// nearest one at or after it (symmetric with `step_back(0)` landing
+    /// on the nearest one at or before it) rather than leaving the
+    /// position unchanged.
+    pub fn step_forward(&mut self, n: usize) -> Result<&S, T> {
+        let current = match self.current_time {
+            Some(current) => current,
+            None => return Ok(&self.current)
+        };
+        self.move_to(current);
+
+        // If `current` is itself a committed change, it's the 0th step;
+        // stepping further walks into `forward`, newest-applied-first, so
+        // counting from its back (the nearest not-yet-applied change)
+        // walks ascending just like `reverse` does for `step_back`.
+        let on_change = self.reverse.back().map_or(false, |d| d.0 == current);
+        let target = if on_change && n == 0 {
+            current
+        } else {
+            let k = if on_change { n - 1 } else { n };
+            match self.forward.len() {
+                0 => current,
+                len => self.forward[if k >= len { 0 } else { len - 1 - k }].0
+            }
+        };
+
+        self.value_at(target)
+    }
+
+    /// Seeks to `current_time - delta`, the `-` half of `current_time ±
+    /// delta`. A no-op if nothing has been sought or changed yet. The `+`
+    /// half is `offset_forward`, a separate method, so each one carries
+    /// only its own bound (`T: Sub` here, `T: Add` there).
+    pub fn offset(&mut self, delta: T) -> Result<&S, T>
+        where T: Sub<Output = T> {
+        match self.current_time {
+            Some(current) => {
+                let target = current - delta;
+                self.value_at(target)
+            },
+            None => Ok(&self.current)
+        }
+    }
+
+    /// Seeks to `current_time + delta`, the `+` half of `current_time ±
+    /// delta`. A no-op if nothing has been sought or changed yet. See
+    /// also `offset`, which seeks backward.
+    pub fn offset_forward(&mut self, delta: T) -> Result<&S, T>
+        where T: Add<Output = T> {
+        match self.current_time {
+            Some(current) => {
+                let target = current + delta;
+                self.value_at(target)
+            },
+            None => Ok(&self.current)
+        }
+    }
+
     pub fn forget_ancient_history(&mut self, until: T) {
         self.move_forward_to(until);
 
@@ -66,7 +194,7 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
 
     fn check_oldest(&self, at: T) -> Result<(), T> {
         match self.oldest {
-            Some(i) => 
+            Some(i) =>
                 if i > at {
                     Err(Error::TimeEvicted(at, i))
                 } else {
@@ -79,12 +207,13 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
     fn move_to(&mut self, at: T) {
         self.move_forward_to(at);
         self.move_backward_to(at);
+        self.current_time = Some(at);
     }
 
     fn move_backward_to(&mut self, at: T) {
         loop {
             match self.reverse.pop_back() {
-                Some(Timestamped(time, (delta_f, delta_r))) => 
+                Some(Timestamped(time, (delta_f, delta_r))) =>
                     if time <= at {
                         self.reverse.push_back(Timestamped(time, (delta_f, delta_r)));
                         break;
@@ -100,7 +229,7 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
     fn move_forward_to(&mut self, at: T) {
         loop {
             match self.forward.pop() {
-                Some(Timestamped(time, delta)) => 
+                Some(Timestamped(time, delta)) =>
                     if time > at {
                         self.forward.push(Timestamped(time, delta));
                         break;
@@ -112,15 +241,236 @@ impl <S, F, R, T> TimeMachine<S, F, R, T>
             }
         }
     }
+
+    /// Reconstructs the state just before the earliest retained change
+    /// (strictly before `self.oldest` if a change sits exactly there; the
+    /// initial state, if nothing has been forgotten yet) by rewinding a
+    /// clone of `current` back through `reverse`, without disturbing the
+    /// machine itself.
+    #[cfg(feature = "serde")]
+    fn state_as_of_oldest(&self) -> S
+        where S: Clone {
+        let mut state = self.current.clone();
+        for &Timestamped(time, (_, ref delta_r)) in self.reverse.iter().rev() {
+            match self.oldest {
+                // Rewind through the delta timestamped exactly `oldest`
+                // too, so `state` lands strictly before it — it's still
+                // included (and will be replayed) as the first entry in
+                // `deltas`.
+                Some(oldest) if time < oldest => break,
+                _ => state.apply_reverse(delta_r)
+            }
+        }
+        state
+    }
+}
+
+pub struct StatesBetween<'a, S: 'a, F: 'a, R: 'a, T: 'a> {
+    machine: &'a mut TimeMachine<S, F, R, T>,
+    to: T
+}
+
+impl <'a, S, F, R, T> StatesBetween<'a, S, F, R, T>
+    where S: TimeMachineState<F, R>,
+          T: PartialOrd + Copy {
+    pub fn next(&mut self) -> Option<(T, &S)> {
+        match self.machine.forward.pop() {
+            Some(Timestamped(time, delta)) =>
+                if time > self.to {
+                    self.machine.forward.push(Timestamped(time, delta));
+                    None
+                } else {
+                    let new_delta = self.machine.current.apply_forward(&delta);
+                    self.machine.reverse.push_back(Timestamped(time, (delta, new_delta)));
+                    Some((time, &self.machine.current))
+                },
+            None => None
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod persist {
+    use super::{TimeMachine, TimeMachineState, Timestamped};
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct SerializedTimeMachine<S, F, T> {
+        oldest: Option<T>,
+        state_as_of_oldest: S,
+        // Forward deltas in timestamp order, oldest first.
+        deltas: Vec<(T, F)>
+    }
+
+    impl <S, F, R, T> Serialize for TimeMachine<S, F, R, T>
+        where S: TimeMachineState<F, R> + Clone + Serialize,
+              F: Clone + Serialize,
+              T: PartialOrd + Copy + Serialize {
+        fn serialize<Ser>(&self, serializer: Ser) -> ::std::result::Result<Ser::Ok, Ser::Error>
+            where Ser: Serializer {
+            let mut deltas: Vec<(T, F)> = self.reverse.iter()
+                .map(|&Timestamped(time, (ref f, _))| (time, f.clone()))
+                .collect();
+            deltas.extend(self.forward.iter().rev()
+                .map(|&Timestamped(time, ref f)| (time, f.clone())));
+
+            SerializedTimeMachine {
+                oldest: self.oldest,
+                state_as_of_oldest: self.state_as_of_oldest(),
+                deltas: deltas
+            }.serialize(serializer)
+        }
+    }
+
+    impl <'de, S, F, R, T> Deserialize<'de> for TimeMachine<S, F, R, T>
+        where S: TimeMachineState<F, R> + Clone + Deserialize<'de>,
+              F: Deserialize<'de>,
+              T: PartialOrd + Copy + Deserialize<'de> {
+        fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where D: Deserializer<'de> {
+            let serialized: SerializedTimeMachine<S, F, T> = try!(Deserialize::deserialize(deserializer));
+
+            let mut machine = TimeMachine::new(serialized.state_as_of_oldest);
+            machine.oldest = serialized.oldest;
+            machine.forward = serialized.deltas.into_iter()
+                .rev()
+                .map(|(time, delta)| Timestamped(time, delta))
+                .collect();
+            Ok(machine)
+        }
+    }
+}
+
+/// Identifies a single revision within a `BranchingTimeMachine`'s history
+/// tree. Stable for the lifetime of the machine (revisions are never
+/// renumbered or removed).
+pub type RevisionId = usize;
+
+struct Revision<F, R, T> {
+    parent: Option<RevisionId>,
+    time: T,
+    forward: F,
+    reverse: R
+}
+
+/// An undo-tree variant of `TimeMachine`: a `branch` made while sitting on
+/// an older revision forks a new branch instead of rewriting the future,
+/// so divergent histories can be kept and revisited side by side.
+pub struct BranchingTimeMachine<S, F, R, T> {
+    current: S,
+    current_revision: Option<RevisionId>,
+    revisions: Vec<Revision<F, R, T>>
+}
+
+impl <S, F, R, T> BranchingTimeMachine<S, F, R, T>
+    where S: TimeMachineState<F, R>,
+          T: PartialOrd + Copy {
+    pub fn new(initial: S) -> BranchingTimeMachine<S, F, R, T> {
+        BranchingTimeMachine {
+            current: initial,
+            current_revision: None,
+            revisions: Vec::new()
+        }
+    }
+
+    pub fn current(&self) -> &S {
+        &self.current
+    }
+
+    pub fn current_revision(&self) -> Option<RevisionId> {
+        self.current_revision
+    }
+
+    /// Applies `delta` on top of the current revision, forking a new
+    /// branch from it, and returns the id of the revision it created.
+    pub fn branch(&mut self, delta: F, at: T) -> RevisionId {
+        let reverse = self.current.apply_forward(&delta);
+        let id = self.revisions.len();
+        self.revisions.push(Revision {
+            parent: self.current_revision,
+            time: at,
+            forward: delta,
+            reverse: reverse
+        });
+        self.current_revision = Some(id);
+        id
+    }
+
+    /// Moves to `revision`, undoing back to the lowest common ancestor of
+    /// the current revision and `revision`, then redoing forward from
+    /// there.
+    pub fn jump_to(&mut self, revision: RevisionId) {
+        let lca = self.lowest_common_ancestor(self.current_revision, Some(revision));
+
+        let mut node = self.current_revision;
+        while node != lca {
+            let id = node.expect("walked past the root while undoing");
+            self.current.apply_reverse(&self.revisions[id].reverse);
+            node = self.revisions[id].parent;
+        }
+
+        let mut forward_path = Vec::new();
+        let mut node = Some(revision);
+        while node != lca {
+            let id = node.expect("walked past the root while collecting redo path");
+            forward_path.push(id);
+            node = self.revisions[id].parent;
+        }
+        for id in forward_path.into_iter().rev() {
+            self.current.apply_forward(&self.revisions[id].forward);
+        }
+
+        self.current_revision = Some(revision);
+    }
+
+    /// Lists the revisions forked directly from the current revision, in
+    /// the order they were created.
+    pub fn children(&self) -> Vec<RevisionId> {
+        self.revisions.iter()
+            .enumerate()
+            .filter(|&(_, rev)| rev.parent == self.current_revision)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn depth(&self, mut revision: Option<RevisionId>) -> usize {
+        let mut depth = 0;
+        while let Some(id) = revision {
+            depth += 1;
+            revision = self.revisions[id].parent;
+        }
+        depth
+    }
+
+    fn lowest_common_ancestor(&self, mut a: Option<RevisionId>, mut b: Option<RevisionId>) -> Option<RevisionId> {
+        let mut depth_a = self.depth(a);
+        let mut depth_b = self.depth(b);
+
+        while depth_a > depth_b {
+            a = self.revisions[a.unwrap()].parent;
+            depth_a -= 1;
+        }
+        while depth_b > depth_a {
+            b = self.revisions[b.unwrap()].parent;
+            depth_b -= 1;
+        }
+
+        while a != b {
+            a = self.revisions[a.unwrap()].parent;
+            b = self.revisions[b.unwrap()].parent;
+        }
+
+        a
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Error, TimeMachine, TimeMachineState};
+    use super::{Error, TimeMachine, TimeMachineState, BranchingTimeMachine};
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone)]
     struct TestTimeMachineState(i32);
-    
+
     enum TestTimeMachineDelta {
         Add(i32),
         Sub(i32),
@@ -162,6 +512,7 @@ mod tests {
     }
 
     type TestTimeMachine = TimeMachine<TestTimeMachineState, TestTimeMachineDelta, TestTimeMachineDelta, u32>;
+    type TestBranchingTimeMachine = BranchingTimeMachine<TestTimeMachineState, TestTimeMachineDelta, TestTimeMachineDelta, u32>;
 
     fn assert_machine_success(m: &mut TestTimeMachine, at: u32, expected: i32) {
         let result = m.value_at(at).unwrap();
@@ -230,4 +581,66 @@ mod tests {
         assert_machine_success(&mut m, 3, 18);
         assert_machine_success(&mut m, 4, 8);
     }
+
+    #[test]
+    fn states_between_walks_each_change_once() {
+        let mut m = TestTimeMachine::new(TestTimeMachineState(5));
+        m.change(TestTimeMachineDelta::Add(3), 1).unwrap();
+        m.change(TestTimeMachineDelta::Mul(2), 5).unwrap();
+        m.change(TestTimeMachineDelta::Sub(1), 10).unwrap();
+
+        let mut seen = Vec::new();
+        {
+            let mut states = m.states_between(1, 10).unwrap();
+            while let Some((time, state)) = states.next() {
+                seen.push((time, state.0));
+            }
+        }
+
+        assert_eq!(vec![(1, 8), (5, 16), (10, 15)], seen);
+    }
+
+    #[test]
+    fn relative_seeking() {
+        let mut m = TestTimeMachine::new(TestTimeMachineState(5));
+        m.change(TestTimeMachineDelta::Add(3), 1).unwrap();
+        m.change(TestTimeMachineDelta::Mul(2), 5).unwrap();
+        m.change(TestTimeMachineDelta::Sub(1), 10).unwrap();
+
+        assert_machine_success(&mut m, 10, 15);
+
+        assert_eq!(&TestTimeMachineState(16), m.step_back(1).unwrap());
+        assert_eq!(&TestTimeMachineState(8), m.step_back(1).unwrap());
+        // Clamps at the earliest recorded change.
+        assert_eq!(&TestTimeMachineState(8), m.step_back(5).unwrap());
+
+        assert_eq!(&TestTimeMachineState(16), m.step_forward(1).unwrap());
+        // Clamps at the most recent recorded change.
+        assert_eq!(&TestTimeMachineState(15), m.step_forward(5).unwrap());
+
+        assert_eq!(&TestTimeMachineState(16), m.offset(5).unwrap());
+        assert_eq!(&TestTimeMachineState(15), m.offset_forward(5).unwrap());
+    }
+
+    #[test]
+    fn branching_forks_instead_of_overwriting() {
+        let mut m = TestBranchingTimeMachine::new(TestTimeMachineState(5));
+        let r1 = m.branch(TestTimeMachineDelta::Add(3), 1);
+        assert_eq!(&TestTimeMachineState(8), m.current());
+
+        let r2 = m.branch(TestTimeMachineDelta::Mul(2), 2);
+        assert_eq!(&TestTimeMachineState(16), m.current());
+
+        m.jump_to(r1);
+        assert_eq!(&TestTimeMachineState(8), m.current());
+
+        let r3 = m.branch(TestTimeMachineDelta::Sub(1), 2);
+        assert_eq!(&TestTimeMachineState(7), m.current());
+
+        m.jump_to(r1);
+        assert_eq!(vec![r2, r3], m.children());
+
+        m.jump_to(r2);
+        assert_eq!(&TestTimeMachineState(16), m.current());
+    }
 }